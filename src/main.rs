@@ -1,8 +1,10 @@
-use csv::Reader;
+use csv::{Reader, StringRecord};
 use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
 use clap::Parser;
 use std::collections::{HashMap, HashSet};
 use thiserror::Error;
+use serde_json::{Map, Value};
 
 // cargo run -- --input sample.csv --output result.json --stats
 
@@ -18,27 +20,193 @@ struct Args {
 
     #[arg(short, long, help = "統計情報を表示する")]
     stats: bool,
+
+    #[arg(short, long, value_enum, help = "出力フォーマット（省略時: json、--stream指定時は自動でndjson）")]
+    format: Option<OutputFormat>,
+
+    #[arg(long, value_delimiter = ',', help = "出力する列名（カンマ区切り、指定順）")]
+    columns: Option<Vec<String>>,
+
+    #[arg(long, help = "先頭からN行だけ変換する")]
+    head: Option<usize>,
+
+    #[arg(long, help = "先頭のN行を読み飛ばす")]
+    skip: Option<usize>,
+
+    #[arg(long, help = "全行をメモリに保持せず、読み込みながら逐次書き出す（--format ndjson 選択時は自動で有効）")]
+    stream: bool,
+
+    #[arg(long, default_value_t = ',', help = "区切り文字（セミコロン区切りCSVなら ';'、TSVならタブ文字そのもの（bashでは $'\\t'）を指定。1文字のみ受け付ける）")]
+    delimiter: char,
+
+    #[arg(long = "no-headers", help = "先頭行をヘッダー扱いせず、col_0, col_1... を自動生成する")]
+    no_headers: bool,
+
+    #[arg(long, help = "フィールドとヘッダーの前後の空白をトリムする")]
+    trim: bool,
+}
+
+/// `--format` で選択できる出力フォーマット。
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Json,
+    Ndjson,
+    Yaml,
+    Toml,
+}
+
+impl OutputFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::Toml => "toml",
+        }
+    }
 }
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 enum ConversionError {
     #[error("🚨 ファイルが見つかりません: {path}\n💡 解決方法: ファイルパスを確認してください")]
     FileNotFound { path: String },
-    
+
     #[error("🚨 ファイル読み込みエラー: {path}\n💡 解決方法: ファイルの権限を確認してください")]
     FileReadError { path: String },
-    
+
     #[error("🚨 CSVファイルの形式が不正です\n💡 解決方法: CSV形式を確認してください（ヘッダー行、区切り文字など）")]
     CsvParseError,
-    
+
     #[error("🚨 CSVデータの読み込みでエラーが発生しました: {line}\n💡 解決方法: {line}行目のデータを確認してください")]
     CsvRecordError { line: usize },
-    
+
     #[error("🚨 JSON変換でエラーが発生しました\n💡 解決方法: CSVデータに特殊文字が含まれている可能性があります")]
     JsonConversionError,
-    
+
     #[error("🚨 ファイル書き込みエラー: {path}\n💡 解決方法: 書き込み権限とディスクの空き容量を確認してください")]
     FileWriteError { path: String },
+
+    #[error("🚨 型変換エラー: {line}行目「{column}」列の値「{value}」を数値に変換できません\n💡 解決方法: ヘッダーの型指定（:number）と実際のデータを確認してください")]
+    TypeMismatch { line: usize, column: String, value: String },
+
+    #[error("🚨 YAML変換でエラーが発生しました\n💡 解決方法: CSVデータに特殊文字が含まれている可能性があります")]
+    YamlConversionError,
+
+    #[error("🚨 TOML変換でエラーが発生しました\n💡 解決方法: CSVデータに特殊文字が含まれている可能性があります")]
+    TomlConversionError,
+
+    #[error("🚨 存在しない列が指定されました: {name}\n💡 利用可能な列: {available}")]
+    UnknownColumn { name: String, available: String },
+
+    #[error("🚨 CSVの取得に失敗しました: {url}（ステータス: {status}）\n💡 解決方法: URLとネットワーク接続を確認してください")]
+    HttpError { url: String, status: u16 },
+
+    #[error("🚨 ストリーミングモードは --format {format} に対応していません\n💡 解決方法: --format を外す（自動でndjsonになります）か、--format ndjson を指定するか、--stream を外してください")]
+    UnsupportedStreamingFormat { format: String },
+}
+
+/// ヘッダーに付与できる型指定（例: `age:number`）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AllowedType {
+    String,
+    Number,
+    Boolean,
+}
+
+/// `name:type` 形式のヘッダーを列名と型に分解する。
+/// `:` は最後に出現したものだけを区切りとして扱うため、列名自体に `:` を含めてもよい。
+/// 型が未指定・不明な場合は `AllowedType::String` にフォールバックする。
+fn parse_csv_header(header: &str) -> (String, AllowedType) {
+    match header.rsplit_once(':') {
+        Some((name, "number")) => (name.to_string(), AllowedType::Number),
+        Some((name, "boolean")) => (name.to_string(), AllowedType::Boolean),
+        Some((name, "string")) => (name.to_string(), AllowedType::String),
+        Some((name, _)) => (name.to_string(), AllowedType::String),
+        None => (header.to_string(), AllowedType::String),
+    }
+}
+
+/// ヘッダーの型指定に従ってセルの文字列値をJSONの値へ変換する。
+/// 空セルは型によらず `null` になる。
+fn convert_field(
+    value: &str,
+    allowed_type: AllowedType,
+    line: usize,
+    column: &str,
+) -> Result<Value, ConversionError> {
+    if value.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+
+    match allowed_type {
+        AllowedType::String => Ok(Value::String(value.to_string())),
+        AllowedType::Boolean => match value {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => Ok(Value::String(value.to_string())),
+        },
+        AllowedType::Number => {
+            let parsed: f64 = value.parse().map_err(|_| ConversionError::TypeMismatch {
+                line,
+                column: column.to_string(),
+                value: value.to_string(),
+            })?;
+            let number = serde_json::Number::from_f64(parsed).ok_or_else(|| ConversionError::TypeMismatch {
+                line,
+                column: column.to_string(),
+                value: value.to_string(),
+            })?;
+            Ok(Value::Number(number))
+        }
+    }
+}
+
+/// 1行分のNDJSON出力（コンパクトなJSON + 改行）を作る。`serialize_rows`の`ndjson`分岐と
+/// `convert_streaming`の両方から使われ、NDJSONの行フォーマットを一箇所にまとめる。
+fn serialize_ndjson_line(row: &Map<String, Value>) -> Result<String, ConversionError> {
+    serde_json::to_string(row).map_err(|_| ConversionError::JsonConversionError)
+}
+
+/// `--format` の指定に従って変換後の行データを文字列へシリアライズする。
+/// `ndjson` は1行1オブジェクトで書き出し、検索インデクサーやDBへのストリーム投入に向く
+/// （実際のCLI経路では `--format ndjson` は常に `convert_streaming` を通るため、
+/// このアームは直接 `serialize_rows` を呼ぶ場合のために残している）。
+/// `toml` はトップレベルがテーブルである必要があるため、`rows` キーの下に配列を格納する。
+fn serialize_rows(rows: &[Map<String, Value>], format: OutputFormat) -> Result<String, ConversionError> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_string_pretty(rows).map_err(|_| ConversionError::JsonConversionError)
+        }
+        OutputFormat::Ndjson => {
+            let mut lines = Vec::with_capacity(rows.len());
+            for row in rows {
+                lines.push(serialize_ndjson_line(row)?);
+            }
+            Ok(lines.join("\n"))
+        }
+        OutputFormat::Yaml => {
+            serde_yaml::to_string(rows).map_err(|_| ConversionError::YamlConversionError)
+        }
+        OutputFormat::Toml => {
+            // TOMLはnull/空のキーを表現できないため、空セル由来のnullキーは出力前に取り除く。
+            let sanitized_rows: Vec<Value> = rows
+                .iter()
+                .map(|row| {
+                    let sanitized: Map<String, Value> = row
+                        .iter()
+                        .filter(|(_, value)| !value.is_null())
+                        .map(|(key, value)| (key.clone(), value.clone()))
+                        .collect();
+                    Value::Object(sanitized)
+                })
+                .collect();
+
+            let mut wrapped = Map::new();
+            wrapped.insert("rows".to_string(), Value::Array(sanitized_rows));
+            toml::to_string_pretty(&wrapped).map_err(|_| ConversionError::TomlConversionError)
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -74,28 +242,28 @@ impl CsvStats {
     }
 }
 
-fn calculate_stats(data: &[HashMap<String, String>], headers: &csv::StringRecord) -> CsvStats {
+fn calculate_stats(data: &[Map<String, Value>], column_names: &[String]) -> CsvStats {
     let mut stats = CsvStats::new();
 
-    stats.total_rows =data.len();
-    stats.total_columns = headers.len();
+    stats.total_rows = data.len();
+    stats.total_columns = column_names.len();
 
 let mut column_unique_values: HashMap<String, HashSet<String>> = HashMap::new();
 
-for header in headers.iter() {
-    column_unique_values.insert(header.to_string(), HashSet::new());
+for column in column_names {
+    column_unique_values.insert(column.clone(), HashSet::new());
 }
 
 
     for row in data {
         for (column, value) in row {
 
-            if value.trim().is_empty() {
+            if value.is_null() {
                 stats.empty_cells += 1;
             }
 
             if let Some(unique_set) = column_unique_values.get_mut(column) {
-                unique_set.insert(value.clone());
+                unique_set.insert(value.to_string());
             }
         }
     }
@@ -107,39 +275,211 @@ for header in headers.iter() {
     stats
 }
 
-fn convert_dynamic(input_path: &str, output_path: Option<&str>, show_stats: bool) -> Result<(), ConversionError> {
-    // let file = File::open(input_path)?;
+/// `--stream` 経由で1行ずつ届くデータから `CsvStats` と同じ集計をオンラインで行う。
+/// 全行をメモリに溜めずに、行数・空セル数・列ごとのユニーク値集合だけを保持する。
+struct StatsAccumulator {
+    total_rows: usize,
+    empty_cells: usize,
+    column_unique_values: HashMap<String, HashSet<String>>,
+}
+
+impl StatsAccumulator {
+    fn new(column_names: &[String]) -> Self {
+        let mut column_unique_values = HashMap::new();
+        for name in column_names {
+            column_unique_values.insert(name.clone(), HashSet::new());
+        }
+
+        StatsAccumulator {
+            total_rows: 0,
+            empty_cells: 0,
+            column_unique_values,
+        }
+    }
+
+    fn add_row(&mut self, row: &Map<String, Value>) {
+        self.total_rows += 1;
+
+        for (column, value) in row {
+            if value.is_null() {
+                self.empty_cells += 1;
+            }
+
+            if let Some(unique_set) = self.column_unique_values.get_mut(column) {
+                unique_set.insert(value.to_string());
+            }
+        }
+    }
+
+    fn into_stats(self) -> CsvStats {
+        let mut stats = CsvStats::new();
+        stats.total_rows = self.total_rows;
+        stats.total_columns = self.column_unique_values.len();
+        stats.empty_cells = self.empty_cells;
 
-    let file = File::open(input_path).map_err(|e| {
-        match e.kind() {
-            std::io::ErrorKind::NotFound => ConversionError::FileNotFound { path: input_path.to_string(),
+        for (column, unique_set) in self.column_unique_values {
+            stats.column_unique_counts.insert(column, unique_set.len());
+        }
+
+        stats
+    }
+}
+
+/// 選択済みの列定義に従って1レコードをJSONの行オブジェクトに変換する。
+fn build_row(
+    record: &StringRecord,
+    selected: &[(String, AllowedType, usize)],
+    line_num: usize,
+) -> Result<Map<String, Value>, ConversionError> {
+    let mut row_map = Map::new();
+
+    for (name, allowed_type, index) in selected {
+        let field = record.get(*index).unwrap_or("");
+        let value = convert_field(field, *allowed_type, line_num, name)?;
+        row_map.insert(name.clone(), value);
+    }
+
+    Ok(row_map)
+}
+
+/// 列の射影（`--columns`）を解決し、`(列名, 型, 元のフィールド位置)` のリストを返す。
+/// 指定が無ければヘッダー順そのままを使う。存在しない列名が指定された場合はエラーにする。
+fn resolve_selected_columns(
+    columns: &[(String, AllowedType)],
+    requested: Option<&[String]>,
+) -> Result<Vec<(String, AllowedType, usize)>, ConversionError> {
+    match requested {
+        Some(names) => {
+            let available: Vec<&String> = columns.iter().map(|(name, _)| name).collect();
+            let mut selected = Vec::with_capacity(names.len());
+            for name in names {
+                let index = available.iter().position(|c| *c == name).ok_or_else(|| {
+                    ConversionError::UnknownColumn {
+                        name: name.clone(),
+                        available: available.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", "),
+                    }
+                })?;
+                selected.push((columns[index].0.clone(), columns[index].1, index));
+            }
+            Ok(selected)
+        }
+        None => Ok(columns
+            .iter()
+            .enumerate()
+            .map(|(i, (name, allowed_type))| (name.clone(), *allowed_type, i))
+            .collect()),
+    }
+}
+
+/// `--input` がローカルパスかURLかを判定し、どちらの場合も読み込み用の `Read` を返す。
+/// URLの場合はブロッキングHTTPクライアントで取得し、本文はバッファリングしながらストリームで渡す
+/// （巨大なレスポンスを `String` に丸ごと保持しない）。
+fn open_input(input_path: &str) -> Result<Box<dyn Read>, ConversionError> {
+    if input_path.starts_with("http://") || input_path.starts_with("https://") {
+        let response = ureq::get(input_path).call().map_err(|e| match e {
+            ureq::Error::Status(status, _) => ConversionError::HttpError {
+                url: input_path.to_string(),
+                status,
             },
-            _ => ConversionError::FileReadError { 
-            path: input_path.to_string(),
+            ureq::Error::Transport(_) => ConversionError::HttpError {
+                url: input_path.to_string(),
+                status: 0,
             },
-        }
-    })?;
+        })?;
+        Ok(Box::new(BufReader::new(response.into_reader())))
+    } else {
+        let file = File::open(input_path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ConversionError::FileNotFound { path: input_path.to_string() },
+            _ => ConversionError::FileReadError { path: input_path.to_string() },
+        })?;
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
 
-    let mut reader = Reader::from_reader(file);
+fn convert_dynamic(args: &Args) -> Result<(), ConversionError> {
+    let input_path = args.input.as_str();
 
-    let headers = reader.headers().map_err(|_| ConversionError::CsvParseError)?.clone();
+    let input = open_input(input_path)?;
+
+    if !args.delimiter.is_ascii() {
+        return Err(ConversionError::CsvParseError);
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(args.delimiter as u8)
+        .has_headers(!args.no_headers)
+        .trim(if args.trim { csv::Trim::All } else { csv::Trim::None })
+        .from_reader(input);
+
+    let raw_headers = reader.headers().map_err(|_| ConversionError::CsvParseError)?.clone();
+
+    // `--no-headers` 時は `reader.headers()` が先頭レコードを覗き見しているだけで、
+    // そのレコード自体は `has_headers(false)` により引き続きデータ行として読み出される。
+    let headers = if args.no_headers {
+        let synthetic: Vec<String> = (0..raw_headers.len()).map(|i| format!("col_{}", i)).collect();
+        StringRecord::from(synthetic)
+    } else {
+        raw_headers
+    };
     println!("ヘッダー読み込み完了：{:?}", headers);
 
-    let mut all_rows: Vec<HashMap<String, String>> = Vec::new();
+    let columns: Vec<(String, AllowedType)> = headers.iter().map(parse_csv_header).collect();
+    let selected = resolve_selected_columns(&columns, args.columns.as_deref())?;
+    let selected_names: Vec<String> = selected.iter().map(|(name, _, _)| name.clone()).collect();
+
+    let (format, streaming) = resolve_format(args.format, args.stream)?;
+
+    if streaming {
+        convert_streaming(args, &mut reader, &selected, &selected_names)
+    } else {
+        convert_batch(args, format, &mut reader, &selected, &selected_names)
+    }
+}
+
+/// `--format` と `--stream` の組み合わせから、実際に使うフォーマットとストリーミングするかどうかを決める。
+/// `--format ndjson` は常にストリーミング経路を使う。`--stream` 単体（`--format` 省略）はndjsonを選んだものとして
+/// 扱うが、`--stream` と ndjson以外の明示的な `--format` が両方指定された場合はエラーにする
+/// （黙ってNDJSONへすり替えるとユーザーの指定と異なる形式が出力されてしまうため）。
+fn resolve_format(requested: Option<OutputFormat>, stream: bool) -> Result<(OutputFormat, bool), ConversionError> {
+    match (requested, stream) {
+        (Some(OutputFormat::Ndjson), _) => Ok((OutputFormat::Ndjson, true)),
+        (Some(format), true) => Err(ConversionError::UnsupportedStreamingFormat {
+            format: format.as_str().to_string(),
+        }),
+        (Some(format), false) => Ok((format, false)),
+        (None, true) => Ok((OutputFormat::Ndjson, true)),
+        (None, false) => Ok((OutputFormat::Json, false)),
+    }
+}
+
+/// 全行を `Vec` に保持してから一括シリアライズする従来どおりの変換経路。
+fn convert_batch(
+    args: &Args,
+    format: OutputFormat,
+    reader: &mut Reader<Box<dyn Read>>,
+    selected: &[(String, AllowedType, usize)],
+    selected_names: &[String],
+) -> Result<(), ConversionError> {
+    let skip_rows = args.skip.unwrap_or(0);
+
+    let mut all_rows: Vec<Map<String, Value>> = Vec::new();
 
     for (line_num, result) in reader.records().enumerate() {
-        let record = result.map_err(|_| ConversionError::CsvRecordError { 
+        if let Some(head_rows) = args.head {
+            if all_rows.len() >= head_rows {
+                break;
+            }
+        }
+
+        let record = result.map_err(|_| ConversionError::CsvRecordError {
             line: line_num + 2
         })?;
-        let mut row_map = HashMap::new();
 
-        for (i, field) in record.iter().enumerate() {
-            if let Some(header) = headers.get(i) {
-                row_map.insert(header.to_string(), field.to_string());
-            }
+        if line_num < skip_rows {
+            continue;
         }
 
-        all_rows.push(row_map);
+        all_rows.push(build_row(&record, selected, line_num + 2)?);
     }
 
     println!("全{}行のデータ読み込み完了:", all_rows.len());
@@ -150,34 +490,103 @@ fn convert_dynamic(input_path: &str, output_path: Option<&str>, show_stats: bool
         println!("...(他{}行)", all_rows.len() -3);
     }
 
-    if show_stats {
-        let stats = calculate_stats(&all_rows, &headers);
+    if args.stats {
+        let stats = calculate_stats(&all_rows, selected_names);
         stats.display();
     }
 
-    let json_output = serde_json::to_string_pretty(&all_rows).map_err(|_| ConversionError::JsonConversionError)?;
+    let output = serialize_rows(&all_rows, format)?;
 
-    match output_path {
+    match args.output.as_deref() {
         Some(path) => {
-            std::fs::write(path, json_output).map_err(|_| ConversionError::FileWriteError { path: path.to_string(), })?;
-            println!("JSONファイルを保存しました：{}", path);
+            std::fs::write(path, output).map_err(|_| ConversionError::FileWriteError { path: path.to_string(), })?;
+            println!("ファイルを保存しました：{}", path);
         }
         None => {
-            println!("JSON出力：");
-            println!("{}", json_output);
+            println!("変換結果：");
+            println!("{}", output);
         }
     }
 
     Ok(())
 }
 
+/// 全行をメモリに保持せず、読み込んだレコードをその場でNDJSON行として書き出す変換経路。
+/// 巨大なCSV（GB級のcrates.io db-dumpなど）でも行データをメモリに溜めない。
+/// ただし `--stats` 指定時は列ごとのユニーク値集合を保持するため、その分のメモリはデータ量に応じて増える。
+fn convert_streaming(
+    args: &Args,
+    reader: &mut Reader<Box<dyn Read>>,
+    selected: &[(String, AllowedType, usize)],
+    selected_names: &[String],
+) -> Result<(), ConversionError> {
+    println!("ストリーミングモードで変換します（--stream）");
+
+    let output_label = args.output.clone().unwrap_or_else(|| "stdout".to_string());
+
+    let mut writer: BufWriter<Box<dyn Write>> = match &args.output {
+        Some(path) => {
+            let file = File::create(path).map_err(|_| ConversionError::FileWriteError { path: path.clone() })?;
+            BufWriter::new(Box::new(file))
+        }
+        None => BufWriter::new(Box::new(std::io::stdout())),
+    };
+
+    let skip_rows = args.skip.unwrap_or(0);
+    let mut stats_acc = args.stats.then(|| StatsAccumulator::new(selected_names));
+    let mut emitted = 0usize;
+
+    for (line_num, result) in reader.records().enumerate() {
+        if let Some(head_rows) = args.head {
+            if emitted >= head_rows {
+                break;
+            }
+        }
+
+        let record = result.map_err(|_| ConversionError::CsvRecordError {
+            line: line_num + 2
+        })?;
+
+        if line_num < skip_rows {
+            continue;
+        }
+
+        let row = build_row(&record, selected, line_num + 2)?;
+
+        if let Some(acc) = stats_acc.as_mut() {
+            acc.add_row(&row);
+        }
+
+        let line = serialize_ndjson_line(&row)?;
+        writer
+            .write_all(line.as_bytes())
+            .and_then(|_| writer.write_all(b"\n"))
+            .map_err(|_| ConversionError::FileWriteError { path: output_label.clone() })?;
+
+        emitted += 1;
+    }
+
+    writer.flush().map_err(|_| ConversionError::FileWriteError { path: output_label.clone() })?;
+
+    println!("全{}行のデータをストリーミング書き出し完了:", emitted);
+    if args.output.is_some() {
+        println!("ファイルを保存しました：{}", output_label);
+    }
+
+    if let Some(acc) = stats_acc {
+        acc.into_stats().display();
+    }
+
+    Ok(())
+}
+
 fn main() {
     let args = Args::parse();
 
     println!("csv 読み込み開始 ファイル: {}", args.input);
     println!("─────────────────────────────────────");
 
-    if let Err(e) = convert_dynamic(&args.input, args.output.as_deref(), args.stats) {
+    if let Err(e) = convert_dynamic(&args) {
         eprintln!("\n{}", e);
         std::process::exit(1);
     };
@@ -185,3 +594,135 @@ fn main() {
     println!("─────────────────────────────────────");
     println!("🎉 変換完了！お疲れ様でした〜");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_csv_header_splits_on_last_colon() {
+        assert_eq!(parse_csv_header("age:number"), ("age".to_string(), AllowedType::Number));
+        assert_eq!(parse_csv_header("active:boolean"), ("active".to_string(), AllowedType::Boolean));
+        assert_eq!(parse_csv_header("name:string"), ("name".to_string(), AllowedType::String));
+        assert_eq!(parse_csv_header("a:b:number"), ("a:b".to_string(), AllowedType::Number));
+    }
+
+    #[test]
+    fn parse_csv_header_defaults_to_string_without_suffix() {
+        assert_eq!(parse_csv_header("name"), ("name".to_string(), AllowedType::String));
+    }
+
+    #[test]
+    fn parse_csv_header_keeps_name_for_unknown_suffix() {
+        assert_eq!(parse_csv_header("ratio:percent"), ("ratio".to_string(), AllowedType::String));
+    }
+
+    #[test]
+    fn convert_field_empty_becomes_null() {
+        assert_eq!(convert_field("", AllowedType::Number, 2, "age").unwrap(), Value::Null);
+        assert_eq!(convert_field("   ", AllowedType::String, 2, "name").unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn convert_field_parses_number_and_boolean() {
+        assert_eq!(
+            convert_field("42", AllowedType::Number, 2, "age").unwrap(),
+            Value::Number(serde_json::Number::from_f64(42.0).unwrap())
+        );
+        assert_eq!(convert_field("true", AllowedType::Boolean, 2, "active").unwrap(), Value::Bool(true));
+        assert_eq!(convert_field("false", AllowedType::Boolean, 2, "active").unwrap(), Value::Bool(false));
+        assert_eq!(
+            convert_field("yes", AllowedType::Boolean, 2, "active").unwrap(),
+            Value::String("yes".to_string())
+        );
+    }
+
+    #[test]
+    fn convert_field_rejects_non_numeric_value() {
+        let err = convert_field("abc", AllowedType::Number, 3, "age").unwrap_err();
+        assert!(matches!(err, ConversionError::TypeMismatch { line: 3, .. }));
+    }
+
+    #[test]
+    fn convert_field_rejects_non_finite_numeric_value() {
+        for value in ["NaN", "inf", "infinity", "1e400"] {
+            let err = convert_field(value, AllowedType::Number, 5, "age").unwrap_err();
+            assert!(matches!(err, ConversionError::TypeMismatch { line: 5, .. }), "{value} should be rejected");
+        }
+    }
+
+    #[test]
+    fn resolve_selected_columns_reorders_to_requested_order() {
+        let columns = vec![
+            ("zebra".to_string(), AllowedType::String),
+            ("age".to_string(), AllowedType::Number),
+            ("mango".to_string(), AllowedType::String),
+        ];
+        let requested = vec!["mango".to_string(), "age".to_string(), "zebra".to_string()];
+
+        let selected = resolve_selected_columns(&columns, Some(&requested)).unwrap();
+        let names: Vec<&str> = selected.iter().map(|(name, _, _)| name.as_str()).collect();
+
+        assert_eq!(names, vec!["mango", "age", "zebra"]);
+    }
+
+    #[test]
+    fn resolve_selected_columns_rejects_unknown_column() {
+        let columns = vec![("name".to_string(), AllowedType::String), ("age".to_string(), AllowedType::Number)];
+        let requested = vec!["email".to_string()];
+
+        let err = resolve_selected_columns(&columns, Some(&requested)).unwrap_err();
+        match err {
+            ConversionError::UnknownColumn { name, available } => {
+                assert_eq!(name, "email");
+                assert_eq!(available, "name, age");
+            }
+            other => panic!("expected UnknownColumn, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn build_row_preserves_requested_column_order_in_output() {
+        let record = StringRecord::from(vec!["1", "2", "3"]);
+        let selected = vec![
+            ("mango".to_string(), AllowedType::Number, 2),
+            ("age".to_string(), AllowedType::Number, 1),
+            ("zebra".to_string(), AllowedType::Number, 0),
+        ];
+
+        let row = build_row(&record, &selected, 2).unwrap();
+        let keys: Vec<&String> = row.keys().collect();
+
+        assert_eq!(keys, vec!["mango", "age", "zebra"]);
+
+        let json = serde_json::to_string(&row).unwrap();
+        assert_eq!(json, r#"{"mango":3.0,"age":2.0,"zebra":1.0}"#);
+    }
+
+    #[test]
+    fn resolve_format_defaults_and_stream_shorthand() {
+        assert!(matches!(resolve_format(None, false), Ok((OutputFormat::Json, false))));
+        assert!(matches!(resolve_format(None, true), Ok((OutputFormat::Ndjson, true))));
+        assert!(matches!(resolve_format(Some(OutputFormat::Ndjson), false), Ok((OutputFormat::Ndjson, true))));
+    }
+
+    #[test]
+    fn resolve_format_rejects_stream_with_conflicting_explicit_format() {
+        for format in [OutputFormat::Json, OutputFormat::Yaml, OutputFormat::Toml] {
+            let err = resolve_format(Some(format), true).unwrap_err();
+            assert!(matches!(err, ConversionError::UnsupportedStreamingFormat { .. }));
+        }
+    }
+
+    #[test]
+    fn serialize_rows_drops_null_keys_for_toml() {
+        let mut row = Map::new();
+        row.insert("name".to_string(), Value::String("Alice".to_string()));
+        row.insert("nickname".to_string(), Value::Null);
+
+        let output = serialize_rows(&[row], OutputFormat::Toml).unwrap();
+
+        assert!(output.contains("name"));
+        assert!(!output.contains("nickname"));
+    }
+}